@@ -0,0 +1,71 @@
+
+use std::collections::HashMap;
+
+use serenity::http::Http;
+use serenity::model::prelude::{ChannelId, GuildId};
+
+use crate::store::PendingDeletion;
+
+// The maximum number of characters of the original message included in the
+// audit-log embed; long messages are truncated to keep the log readable.
+const CONTENT_SNIPPET_LEN: usize = 500;
+
+// Posts an audit-log embed to a guild's configured reporting channel each time
+// the retention policy removes a message. Guilds without a log channel are
+// simply skipped.
+pub struct Logger {
+    log_channels: HashMap<GuildId, ChannelId>,
+}
+
+impl Logger {
+    pub fn new(log_channels: HashMap<GuildId, ChannelId>) -> Logger {
+        Logger { log_channels }
+    }
+
+    // Announce a deletion in the guild's log channel. Does nothing when the
+    // guild has no log channel configured. The content is taken from the cached
+    // `PendingDeletion` because Discord no longer returns it post-deletion.
+    pub async fn log(&self, http: impl AsRef<Http>, deletion: &PendingDeletion) {
+        let log_channel = match self.log_channels.get(&deletion.guild_id()) {
+            Some(log_channel) => *log_channel,
+            None => return,
+        };
+
+        let snippet = snippet(&deletion.content);
+        let channel_id = deletion.channel_id();
+        let author = deletion.author.clone();
+        let timestamp = deletion.timestamp;
+
+        let result = log_channel
+            .send_message(&http, |message| {
+                message.embed(|embed| {
+                    embed
+                        .title("Message deleted")
+                        .field("Author", &author, true)
+                        .field("Channel", format!("<#{}>", channel_id), true)
+                        .field("Posted", timestamp.to_rfc2822(), false)
+                        .field("Content", snippet, false)
+                })
+            })
+            .await;
+
+        if let Err(why) = result {
+            println!("Error writing audit-log entry to {}: {}", log_channel, why);
+        }
+    }
+}
+
+// Trim the cached content to a single embed-friendly snippet, appending an
+// ellipsis when the message was longer than the snippet limit.
+fn snippet(content: &str) -> String {
+    if content.is_empty() {
+        return "*(no content)*".to_string();
+    }
+
+    if content.chars().count() > CONTENT_SNIPPET_LEN {
+        let truncated: String = content.chars().take(CONTENT_SNIPPET_LEN).collect();
+        format!("{}…", truncated)
+    } else {
+        content.to_string()
+    }
+}
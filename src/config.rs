@@ -0,0 +1,126 @@
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+use serenity::model::prelude::{ChannelId, GuildId};
+
+// The on-disk representation of the configuration file. We keep this separate
+// from the runtime `Config` so that the TOML can stay human-friendly (integer
+// IDs, duration strings like "30m") while the handler works with the parsed
+// types it actually needs.
+#[derive(Debug, Deserialize)]
+struct RawConfig {
+    channel: Vec<RawChannelPolicy>,
+    // Optional per-guild settings, currently just the audit-log channel.
+    #[serde(default)]
+    guild: Vec<RawGuildConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawGuildConfig {
+    guild_id: u64,
+    log_channel: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawChannelPolicy {
+    guild_id: u64,
+    channel_id: u64,
+    max_age: String,
+    // Content patterns that cause a message to be deleted the moment it is
+    // posted, regardless of age. Optional so a channel can rely purely on
+    // age-based expiry.
+    #[serde(default)]
+    patterns: Vec<String>,
+}
+
+// The retention configuration for the bot. `retention` maps each (guild,
+// channel) pair the bot should manage to the maximum age a message in that
+// channel may reach before it is deleted; `rules` maps the same pairs to the
+// content patterns that trigger immediate deletion.
+pub struct Config {
+    pub retention: HashMap<(GuildId, ChannelId), Duration>,
+    pub rules: HashMap<(GuildId, ChannelId), Vec<Regex>>,
+    pub log_channels: HashMap<GuildId, ChannelId>,
+}
+
+impl Config {
+    // Load and validate the configuration from a TOML file. Any malformed
+    // duration or unreadable file is reported as an error so that startup can
+    // fail loudly rather than silently enforcing the wrong retention window.
+    pub fn from_file(path: &str) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("could not read config file {}: {}", path, err))?;
+        let raw: RawConfig = toml::from_str(&contents)
+            .map_err(|err| format!("could not parse config file {}: {}", path, err))?;
+
+        let mut retention = HashMap::new();
+        let mut rules = HashMap::new();
+        for policy in raw.channel {
+            let key = (GuildId(policy.guild_id), ChannelId(policy.channel_id));
+
+            let max_age = parse_duration(&policy.max_age)?;
+            retention.insert(key, max_age);
+
+            // Compile the content patterns once at startup so a malformed
+            // regex fails loudly here rather than silently never matching.
+            let mut patterns = Vec::new();
+            for pattern in policy.patterns {
+                let compiled = Regex::new(&pattern)
+                    .map_err(|err| format!("invalid pattern '{}': {}", pattern, err))?;
+                patterns.push(compiled);
+            }
+            if !patterns.is_empty() {
+                rules.insert(key, patterns);
+            }
+        }
+
+        let mut log_channels = HashMap::new();
+        for guild in raw.guild {
+            log_channels.insert(GuildId(guild.guild_id), ChannelId(guild.log_channel));
+        }
+
+        Ok(Config { retention, rules, log_channels })
+    }
+}
+
+// Parse a human-friendly duration such as "30m", "2h", "90s" or "1d" into a
+// `Duration`. The trailing character selects the unit and the leading digits
+// are the count; anything else is rejected.
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("duration is empty".to_string());
+    }
+
+    // Split on the final character rather than the final byte so a multibyte
+    // trailing character reports a clear error instead of panicking on a
+    // non-char-boundary.
+    let unit_index = input
+        .char_indices()
+        .last()
+        .map(|(index, _)| index)
+        .expect("input is non-empty");
+    let (value, unit) = input.split_at(unit_index);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{}': expected digits before the unit", input))?;
+
+    let seconds = match unit {
+        "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        "d" => value * 86400,
+        other => {
+            return Err(format!(
+                "invalid duration unit '{}' in '{}': expected one of s, m, h, d",
+                other, input
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs(seconds))
+}
@@ -1,6 +1,8 @@
 
 
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
 use std::time::Duration;
 
 use chrono::prelude::*;
@@ -10,34 +12,141 @@ use serenity::{
     prelude::*
 };
 
-const MAX_MESSAGE_AGE: Duration = Duration::from_secs(60 * 30);
+mod audit;
+mod config;
+mod scheduler;
+mod store;
+
+use audit::Logger;
+use config::Config;
+use regex::Regex;
+use scheduler::Scheduler;
+use serenity::http::Http;
+use store::{PendingDeletion, Store};
 
 // Our implementation of the event handler for the Discord gateway.
-// Stores the currently active Guild ID and Channel ID to ensure that it only
-// deletes messages for a specific channel in a specific guild.
+// Holds the retention policy for each (guild, channel) pair the bot manages so
+// that a single process can enforce a different maximum message age in each
+// channel it watches.
 struct Handler {
-    active_guild_id: GuildId,
-    active_channel_id: ChannelId
+    retention: HashMap<(GuildId, ChannelId), Duration>,
+    rules: HashMap<(GuildId, ChannelId), Vec<Regex>>,
+    store: Arc<Store>,
+    logger: Arc<Logger>,
+    scheduler: Scheduler
+}
+
+impl Handler {
+    // Sweep the history of a single channel, deleting every message that is
+    // already older than the channel's retention window. Pinned messages and
+    // messages still inside the window are left untouched.
+    async fn sweep_channel(&self, ctx: &Context, channel: &GuildChannel, max_age: Duration) {
+        // Check to see if the channel has a last message.
+        if let Some(mut last_message_id) = channel.last_message_id {
+            // Retrieve all of the message history for the channel to delete the messages.
+            // If the message is older than the retention window, then delete it immediately.
+            let mut messages_to_delete: Vec<Message> = Vec::new();
+
+            // Using "before" to get messages before a particular ID is NOT inclusive, which means it
+            // skips the very last message in the channel. We first use "most recent" to make sure we
+            // don't miss any messages.
+            let mut messages_result = channel.messages(&ctx.http, |retriever| {
+                // Get the 50 most recent messages in the channel.
+                retriever
+            }).await;
+
+            let utc_now = Utc::now();
+
+            loop {
+                println!("Loop started. Getting messages.");
+
+                println!("Matching result.");
+                match messages_result {
+                    // messages is a Vec which means that to modify it (using pop)
+                    // we must declare it as mutable.
+                    Ok(messages) => {
+                        println!("Got messages {:?}", messages);
+
+                        if let Some(last_message) = messages.last() {
+                            last_message_id = last_message.id;
+                        } else {
+                            println!("Got no last message.");
+                            break;
+                        }
+
+                        messages_to_delete.extend(messages.into_iter().filter(|m| {
+                            if let Ok(message_age) = utc_now.signed_duration_since(m.timestamp).to_std() {
+                                return !m.pinned && message_age > max_age;
+                            } else {
+                                // This branch will be reached if conversion to a standard library duration fails. This only
+                                // occurs for a negative duration, i.e. the timestamp occurs after the current time, which means
+                                // it is newer than the retention window, so we don't want to delete it.
+                                return false;
+                            }
+                        }));
+                    },
+                    Err(err) => {
+                        println!("Error retrieving messages {:?}", err);
+                        break;
+                    }
+                }
+
+                messages_result = channel.messages(&ctx.http, |retriever| {
+                        // Get the 50 messages before last_message_id (exclusive).
+                        retriever.before(last_message_id)
+                }).await;
+            }
+
+            println!("Messages to delete is {:?}", messages_to_delete);
+
+            // Record each message (with a due-now deadline) so the batch path
+            // can write audit-log entries from the cached metadata, then hand
+            // the whole history sweep to the batch path so the initial purge
+            // uses bulk-delete instead of one call per message.
+            let utc_now = Utc::now();
+            for message in &messages_to_delete {
+                self.store.record(pending_deletion(channel.guild_id, message, utc_now));
+            }
+
+            let message_ids: Vec<MessageId> = messages_to_delete.iter().map(|m| m.id).collect();
+            delete_batch(&ctx.http, &self.store, &self.logger, channel.id, message_ids).await;
+        }
+    }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     // Handle a message.
     async fn message(&self, ctx: Context, message: Message) {
-        // Only schedule the message for deletion if the message is from the active guild and channel.
-        if message.guild_id.unwrap() == self.active_guild_id && message.channel_id == self.active_channel_id {
-            println!("Scheduling message {} for deletion in 30 minutes.", message.id);
-
-            // Spawn a background thread which sleeps for 30 minutes before waking and deleting the message.
-            tokio::spawn(async move {
-                tokio::time::sleep(MAX_MESSAGE_AGE).await;
-                match message.delete(ctx.http).await {
-                    Ok(_) => println!("Successfully deleted message {}!", message.id),
-                    Err(why) => {
-                        println!("Error deleting message {}: {}", message.id, why);
-                    }
-                }
-            });
+        // Only schedule the message for deletion if its channel has a retention
+        // policy configured for it.
+        let guild_id = match message.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+
+        // Content rules take precedence over age-based expiry: if the message
+        // matches any pattern configured for its channel, delete it immediately
+        // rather than letting it live out the retention window.
+        if let Some(rules) = self.rules.get(&(guild_id, message.channel_id)) {
+            if rules.iter().any(|pattern| pattern.is_match(&message.content)) {
+                println!("Message {} matched a content rule; deleting immediately.", message.id);
+                // Record (with a due-now deadline) so the audit log can read the
+                // cached content, then delete straight away.
+                self.store.record(pending_deletion(guild_id, &message, Utc::now()));
+                delete_message(&ctx.http, &self.store, &self.logger, message.channel_id, message.id).await;
+                return;
+            }
+        }
+
+        if let Some(&max_age) = self.retention.get(&(guild_id, message.channel_id)) {
+            println!("Scheduling message {} for deletion in {:?}.", message.id, max_age);
+
+            // Record the pending deletion durably so it survives a restart, then
+            // hand it to the scheduler which owns the single deletion timer.
+            let delete_at = Utc::now() + chrono::Duration::from_std(max_age).unwrap();
+            self.store.record(pending_deletion(guild_id, &message, delete_at));
+            self.scheduler.schedule(delete_at, message.channel_id, message.id);
         }
     }
 
@@ -48,106 +157,197 @@ impl EventHandler for Handler {
     async fn guild_create(&self, ctx: Context, guild: Guild) {
         println!("{:?} is created!", guild.id);
 
-        // Look for the active channel in the channel list.
-        if let Some(channel) = guild.channels.get(&self.active_channel_id) {
-            println!("Found channel {:?}", channel);
-
-            // Check to see if the active channel has a last message.
-            if let Some(mut last_message_id) = channel.last_message_id {
-                // Retrieve all of the message history for the channel to delete the messages.
-                // If the message is older than 30 minutes, then delete it immediately.
-                let mut messages_to_delete: Vec<Message> = Vec::new();
-
-                // Using "before" to get messages before a particular ID is NOT inclusive, which means it
-                // skips the very last message in the channel. We first use "most recent" to make sure we
-                // don't miss any messages.
-                let mut messages_result = channel.messages(&ctx.http, |retriever| {
-                    // Get the 50 most recent messages in the channel.
-                    retriever
-                }).await;
+        // Run a reconciliation sweep for each channel in this guild that has a
+        // retention policy configured for it.
+        for (&(policy_guild_id, channel_id), &max_age) in self.retention.iter() {
+            if policy_guild_id != guild.id {
+                continue;
+            }
 
-                let utc_now = Utc::now();
+            // Look for the watched channel in the channel list.
+            if let Some(channel) = guild.channels.get(&channel_id) {
+                println!("Found channel {:?}", channel);
+                self.sweep_channel(&ctx, channel, max_age).await;
+            }
+        }
+    }
 
-                loop {
-                    println!("Loop started. Getting messages.");
+    // A simple ready event handler to print when the gateway is ready to start sending other events.
+    async fn ready(&self, _: Context, ready: Ready) {
+        println!("{} is connected!", ready.user.name);
+    }
+}
 
-                    println!("Matching result.");
-                    match messages_result {
-                        // messages is a Vec which means that to modify it (using pop)
-                        // we must declare it as mutable.
-                        Ok(messages) => {
-                            println!("Got messages {:?}", messages);
 
-                            if let Some(last_message) = messages.last() {
-                                last_message_id = last_message.id;
-                            } else {
-                                println!("Got no last message.");
-                                break;
-                            }
+// Build a store record for a message, caching the author, content and
+// timestamp so the audit log can report them after the message itself is gone.
+fn pending_deletion(guild_id: GuildId, message: &Message, delete_at: DateTime<Utc>) -> PendingDeletion {
+    PendingDeletion {
+        guild_id: guild_id.0,
+        channel_id: message.channel_id.0,
+        message_id: message.id.0,
+        author: message.author.tag(),
+        content: message.content.clone(),
+        timestamp: message.timestamp,
+        delete_at,
+    }
+}
 
-                            messages_to_delete.extend(messages.into_iter().filter(|m| {
-                                if let Ok(message_age) = utc_now.signed_duration_since(m.timestamp).to_std() {
-                                    return !m.pinned && message_age > MAX_MESSAGE_AGE;
-                                } else {
-                                    // This branch will be reached if conversion to a standard library duration fails. This only
-                                    // occurs for a negative duration, i.e. the timestamp occurs after the current time, which means
-                                    // it is less than 30 minutes old, so we don't want to delete it.
-                                    return false;
-                                }
-                            }));
-                        },
-                        Err(err) => {
-                            println!("Error retrieving messages {:?}", err);
-                            break;
-                        }
-                    }
+// Discord's bulk-delete endpoint removes up to 100 messages per call and only
+// accepts messages younger than 14 days.
+const BULK_DELETE_MAX: usize = 100;
+const BULK_DELETE_MAX_AGE_DAYS: i64 = 14;
 
-                    messages_result = channel.messages(&ctx.http, |retriever| {
-                            // Get the 50 messages before last_message_id (inclusive).
-                            retriever.before(last_message_id)
-                    }).await;
-                }
+// Delete a single message and forget its pending deletion once the delete has
+// succeeded, so the durable store does not keep re-arming it on restart.
+// A successful delete is announced in the guild's audit-log channel using the
+// metadata cached in the store.
+async fn delete_message(http: impl AsRef<Http>, store: &Store, logger: &Logger, channel_id: ChannelId, message_id: MessageId) {
+    let record = store.get(channel_id, message_id);
+    match channel_id.delete_message(&http, message_id).await {
+        Ok(_) => {
+            println!("Successfully deleted message {}!", message_id);
+            if let Some(record) = &record {
+                logger.log(&http, record).await;
+            }
+            // Only forget the row once the delete has actually succeeded; a
+            // transient failure leaves it in place so a restart re-arms it.
+            store.remove(channel_id, message_id);
+        }
+        Err(why) => println!("Error deleting message {}: {}", message_id, why),
+    }
+}
 
-                println!("Messages to delete is {:?}", messages_to_delete);
+// Delete a batch of messages from a single channel, preferring Discord's
+// bulk-delete endpoint to cut API calls. Messages younger than 14 days are
+// removed in chunks of up to 100; anything older (which bulk-delete rejects)
+// falls back to one delete at a time, as does a lone message since bulk-delete
+// requires at least two. Deleted messages are dropped from the store.
+async fn delete_batch(http: impl AsRef<Http>, store: &Store, logger: &Logger, channel_id: ChannelId, message_ids: Vec<MessageId>) {
+    let http = http.as_ref();
+    let cutoff = Utc::now() - chrono::Duration::days(BULK_DELETE_MAX_AGE_DAYS);
 
-                for message_id in messages_to_delete {
-                    match message_id.delete(&ctx.http).await {
-                        Ok(_) => println!("Successfully deleted message {:?}!", message_id),
-                        Err(why) => {
-                            println!("Error deleting message {:?}: {}", message_id, why);
-                        }
+    let (recent, old): (Vec<MessageId>, Vec<MessageId>) = message_ids
+        .into_iter()
+        .partition(|id| id.created_at() > cutoff);
+
+    for chunk in recent.chunks(BULK_DELETE_MAX) {
+        if chunk.len() == 1 {
+            delete_message(http, store, logger, channel_id, chunk[0]).await;
+            continue;
+        }
+
+        match channel_id.delete_messages(http, chunk).await {
+            Ok(_) => {
+                println!("Successfully bulk-deleted {} messages in {}!", chunk.len(), channel_id);
+                for &message_id in chunk {
+                    if let Some(record) = store.get(channel_id, message_id) {
+                        logger.log(http, &record).await;
                     }
+                    store.remove(channel_id, message_id);
                 }
             }
+            Err(why) => println!("Error bulk-deleting messages in {}: {}", channel_id, why),
         }
     }
 
-    // A simple ready event handler to print when the gateway is ready to start sending other events.
-    async fn ready(&self, _: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
+    for message_id in old {
+        delete_message(http, store, logger, channel_id, message_id).await;
     }
 }
 
+// Hand every deletion still owed in the store to the scheduler. Deadlines that
+// have already passed are serviced immediately by the scheduler; the rest wait
+// their turn. This runs once at startup so that deletions scheduled before a
+// restart are not lost.
+fn rearm_pending(scheduler: &Scheduler, store: &Store) {
+    for pending in store.pending() {
+        scheduler.schedule(pending.delete_at, pending.channel_id(), pending.message_id());
+    }
+}
+
+// Parse a "start-end" shard range such as "0-3" into the inclusive `[start,
+// end]` pair that `start_shard_range` expects. Both bounds must be valid
+// integers or startup fails loudly.
+fn parse_shard_range(input: &str) -> [u64; 2] {
+    let (start, end) = input
+        .split_once('-')
+        .expect("SHARD_RANGE must be of the form \"start-end\".");
+    let start = str::parse::<u64>(start.trim()).expect("SHARD_RANGE start is not a valid integer.");
+    let end = str::parse::<u64>(end.trim()).expect("SHARD_RANGE end is not a valid integer.");
+    [start, end]
+}
 
 #[tokio::main]
 async fn main() {
     // The discord token is required to authenticate the bot to the discord API.
     let token = env::var("DISCORD_TOKEN").expect("token");
 
-    // Get the active guild ID and channel ID from the environment.
-    let guild_id_input = env::var("ACTIVE_GUILD_ID").expect("guild_id");
-    let channel_id_input = env::var("ACTIVE_CHANNEL_ID").expect("channel_id");
+    // Load the per-channel retention policies from the configuration file.
+    let config_path = env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".to_string());
+    let config = Config::from_file(&config_path).expect("Error loading configuration.");
+
+    // Load the durable store of pending deletions so they survive restarts.
+    let store_path = env::var("STORE_PATH").unwrap_or_else(|_| "pending.json".to_string());
+    let store = Arc::new(Store::load(&store_path).expect("Error loading store."));
 
-    let guild_id = str::parse::<u64>(&guild_id_input).expect("guild_id is not a valid integer.");
-    let channel_id = str::parse::<u64>(&channel_id_input).expect("channel_id is not a valid integer.");
+    // The audit logger announces each deletion in the configured per-guild log
+    // channel.
+    let logger = Arc::new(Logger::new(config.log_channels));
+
+    // Create the scheduler handle up front so the event handler can enqueue
+    // deletions; the scheduler task itself is spawned once the HTTP client is
+    // available.
+    let (scheduler, scheduler_rx) = Scheduler::new();
 
     // Initialise the client and start connecting to the gateway.
     let mut client = Client::builder(&token)
-        .event_handler(Handler{active_guild_id: GuildId(guild_id), active_channel_id: ChannelId(channel_id)})
+        .event_handler(Handler{
+            retention: config.retention,
+            rules: config.rules,
+            store: Arc::clone(&store),
+            logger: Arc::clone(&logger),
+            scheduler: scheduler.clone()
+        })
         .await
         .expect("Error creating client.");
 
-    if let Err(why) = client.start().await {
+    // Spawn the single scheduler task now that we can hand it an HTTP client.
+    tokio::spawn(scheduler::run(
+        Arc::clone(&client.cache_and_http.http),
+        Arc::clone(&store),
+        Arc::clone(&logger),
+        scheduler_rx,
+    ));
+
+    // Re-arm any deletions that were still pending when the process last stopped
+    // before we start processing new events.
+    rearm_pending(&scheduler, &store);
+
+    // Bring up the gateway connection. By default this runs a single shard, but
+    // sharding can be configured via the environment so that one process can own
+    // shards N..M while another owns the rest, letting the bot scale past
+    // Discord's per-shard guild limit. The guild and message handlers are
+    // per-guild and need no changes as events fan in from multiple shards.
+    let result = if env::var("START_AUTOSHARDED").is_ok() {
+        // Let Discord decide the shard count.
+        client.start_autosharded().await
+    } else if let Ok(shard_count) = env::var("SHARD_COUNT") {
+        let shard_count = str::parse::<u64>(&shard_count).expect("SHARD_COUNT is not a valid integer.");
+        match env::var("SHARD_RANGE") {
+            // Own only a subset of the total shards; another process owns the rest.
+            Ok(range) => {
+                let range = parse_shard_range(&range);
+                client.start_shard_range(range, shard_count).await
+            }
+            // Own every shard in this process.
+            Err(_) => client.start_shards(shard_count).await,
+        }
+    } else {
+        client.start().await
+    };
+
+    if let Err(why) = result {
         println!("Client error: {}", why);
     }
 }
@@ -0,0 +1,101 @@
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use serenity::http::Http;
+use serenity::model::prelude::{ChannelId, MessageId};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::audit::Logger;
+use crate::delete_batch;
+use crate::store::Store;
+
+// A deletion queued with the scheduler: the deadline at which it is due,
+// followed by the message to delete. Ordering the tuple deadline-first means a
+// min-heap (via `Reverse`) always yields the earliest deadline next.
+type Entry = (DateTime<Utc>, ChannelId, MessageId);
+
+// A handle used by event handlers to enqueue deletions. Cloneable so the same
+// queue can be fed from every handler, however many shards are fanning events
+// in.
+#[derive(Clone)]
+pub struct Scheduler {
+    tx: UnboundedSender<Entry>,
+}
+
+impl Scheduler {
+    // Create a scheduler handle together with the receiver that `run` consumes.
+    // The two are split so the handle can be handed to the event handler before
+    // the HTTP client (which `run` needs) has been built.
+    pub fn new() -> (Scheduler, UnboundedReceiver<Entry>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Scheduler { tx }, rx)
+    }
+
+    // Enqueue a message for deletion at `delete_at`.
+    pub fn schedule(&self, delete_at: DateTime<Utc>, channel_id: ChannelId, message_id: MessageId) {
+        // The scheduler task lives for the whole process, so a send failure can
+        // only mean it is gone during shutdown; there is nothing useful to do.
+        let _ = self.tx.send((delete_at, channel_id, message_id));
+    }
+}
+
+// The long-lived scheduler task. Keeps all pending deletions in a single
+// min-heap ordered by deadline and owns one timer at a time, rather than one
+// parked task per message. It loops selecting between the next deadline
+// elapsing and a new deletion arriving; a newly-enqueued earlier deadline wins
+// the race, shortens the sleep and is serviced first.
+pub async fn run(http: Arc<Http>, store: Arc<Store>, logger: Arc<Logger>, mut rx: UnboundedReceiver<Entry>) {
+    let mut heap: BinaryHeap<Reverse<Entry>> = BinaryHeap::new();
+
+    loop {
+        let next_deadline = heap.peek().map(|Reverse((deadline, _, _))| *deadline);
+
+        tokio::select! {
+            // A new deletion arrived; push it and re-evaluate the next deadline.
+            maybe_entry = rx.recv() => {
+                match maybe_entry {
+                    Some(entry) => heap.push(Reverse(entry)),
+                    // Every sender has been dropped: the bot is shutting down.
+                    None => break,
+                }
+            }
+            // The earliest deadline elapsed. When the heap is empty this branch
+            // never fires, so the scheduler simply waits for the next arrival.
+            _ = sleep_until(next_deadline) => {
+                // Collect every entry whose deadline has passed, grouped by
+                // channel, so each channel's due messages can be bulk-deleted
+                // together rather than one HTTP call at a time.
+                let now = Utc::now();
+                let mut due: HashMap<ChannelId, Vec<MessageId>> = HashMap::new();
+                while let Some(Reverse((deadline, _, _))) = heap.peek() {
+                    if *deadline > now {
+                        break;
+                    }
+                    let Reverse((_, channel_id, message_id)) = heap.pop().unwrap();
+                    due.entry(channel_id).or_default().push(message_id);
+                }
+
+                for (channel_id, message_ids) in due {
+                    delete_batch(&http, &store, &logger, channel_id, message_ids).await;
+                }
+            }
+        }
+    }
+}
+
+// Sleep until `deadline`, or forever when there is no deadline to wait for.
+// Deadlines already in the past resolve immediately so overdue deletions are
+// serviced on the next loop iteration.
+async fn sleep_until(deadline: Option<DateTime<Utc>>) {
+    match deadline {
+        Some(deadline) => {
+            let wait = (deadline - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+            tokio::time::sleep(wait).await;
+        }
+        None => std::future::pending::<()>().await,
+    }
+}
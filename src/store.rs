@@ -0,0 +1,115 @@
+
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::model::prelude::{ChannelId, GuildId, MessageId};
+
+// A single pending deletion: the message we still owe a delete for and the
+// instant at which that delete is due. The author, content and timestamp are
+// cached here because Discord no longer returns them once the message has been
+// deleted, and they are needed to write the audit-log entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingDeletion {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub author: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    pub delete_at: DateTime<Utc>,
+}
+
+impl PendingDeletion {
+    pub fn guild_id(&self) -> GuildId {
+        GuildId(self.guild_id)
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        ChannelId(self.channel_id)
+    }
+
+    pub fn message_id(&self) -> MessageId {
+        MessageId(self.message_id)
+    }
+}
+
+// A durable record of the deletions the bot still owes. Backed by a JSON file
+// so that pending deletions survive a restart: a message scheduled just before
+// the process dies is re-armed on the next startup instead of lingering
+// forever.
+pub struct Store {
+    path: String,
+    pending: Mutex<Vec<PendingDeletion>>,
+}
+
+impl Store {
+    // Load the store from disk, treating a missing file as an empty store so
+    // the first run does not need the file to exist.
+    pub fn load(path: &str) -> Result<Store, String> {
+        let pending = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|err| format!("could not parse store file {}: {}", path, err))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(format!("could not read store file {}: {}", path, err)),
+        };
+
+        Ok(Store {
+            path: path.to_string(),
+            pending: Mutex::new(pending),
+        })
+    }
+
+    // A snapshot of every deletion currently owed, used at startup to re-arm
+    // timers.
+    pub fn pending(&self) -> Vec<PendingDeletion> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    // Look up the owed deletion for a specific message, if any. Used to recover
+    // the cached message metadata when writing an audit-log entry.
+    pub fn get(&self, channel_id: ChannelId, message_id: MessageId) -> Option<PendingDeletion> {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.channel_id == channel_id.0 && p.message_id == message_id.0)
+            .cloned()
+    }
+
+    // Record a deletion we owe, persisting the new state immediately.
+    pub fn record(&self, deletion: PendingDeletion) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push(deletion);
+        }
+        self.persist();
+    }
+
+    // Forget a deletion once it has been carried out (or is no longer needed),
+    // persisting the new state immediately.
+    pub fn remove(&self, channel_id: ChannelId, message_id: MessageId) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            pending.retain(|p| !(p.channel_id == channel_id.0 && p.message_id == message_id.0));
+        }
+        self.persist();
+    }
+
+    // Write the current set of pending deletions back to disk. Errors are
+    // reported rather than propagated: a failed write must not stop the bot
+    // from deleting messages, it only weakens the durability guarantee.
+    fn persist(&self) {
+        let pending = self.pending.lock().unwrap();
+        match serde_json::to_string(&*pending) {
+            Ok(contents) => {
+                if let Err(err) = fs::write(&self.path, contents) {
+                    println!("Error persisting store file {}: {}", self.path, err);
+                }
+            }
+            Err(err) => println!("Error serialising store file {}: {}", self.path, err),
+        }
+    }
+}